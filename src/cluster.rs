@@ -0,0 +1,239 @@
+use bit_vec::BitVec;
+use std::cmp;
+
+/// Greedy consensus clustering (SALSO) over accumulated spatial pooler
+/// outputs. Groups similar SDRs without supervision by minimizing the
+/// expected Binder loss against a pairwise co-occurrence ("closeness")
+/// matrix, giving an unsupervised classifier over a layer's representations.
+pub struct SdrClusterer {
+    sdrs: Vec<BitVec>,
+    /// Base seed for the per-restart pseudo-random visiting order.
+    seed: u64
+}
+
+impl Default for SdrClusterer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SdrClusterer {
+    pub fn new() -> Self {
+        Self { sdrs: Vec::new(), seed: 0x9e37_79b9_7f4a_7c15 }
+    }
+
+    /// Accumulates one spatial pooler output, e.g. from
+    /// `HTMLayer::spatial_pooling_output`.
+    pub fn add(&mut self, sdr: BitVec) {
+        self.sdrs.push(sdr);
+    }
+
+    /// Groups the accumulated SDRs into clusters. Runs `restarts`
+    /// independent random restarts of the greedy SALSO procedure (in
+    /// parallel when the `parallel` feature is enabled), keeps the
+    /// partition with the lowest total loss, then "sweetens" it by
+    /// repeatedly re-assigning each item given all the others until no
+    /// reassignment lowers the loss further. Returns a cluster label per
+    /// stored SDR, in insertion order.
+    pub fn cluster(&self, restarts: usize) -> Vec<usize> {
+        let n = self.sdrs.len();
+        if n == 0 { return Vec::new(); }
+
+        let p = self.co_occurrence_matrix();
+
+        #[cfg(feature = "parallel")]
+        let best = {
+            use rayon::prelude::*;
+
+            (0..restarts).into_par_iter()
+                .map(|r| Self::restart(&p, n, self.seed, r as u64))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        };
+        #[cfg(not(feature = "parallel"))]
+        let best = (0..restarts)
+            .map(|r| Self::restart(&p, n, self.seed, r as u64))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let (labels, _) = best.unwrap_or_else(|| ((0..n).collect(), 0.0));
+        Self::sweeten(&p, labels)
+    }
+
+    /// Jaccard similarity of the two SDRs' active columns, used as the
+    /// `p_ij` "closeness" probability.
+    fn closeness(a: &BitVec, b: &BitVec) -> f32 {
+        let mut intersection = 0usize;
+        let mut union = 0usize;
+        for i in 0..cmp::min(a.len(), b.len()) {
+            let (ai, bi) = (a[i], b[i]);
+            if ai || bi { union += 1; }
+            if ai && bi { intersection += 1; }
+        }
+        if union == 0 { 0.0 } else { intersection as f32 / union as f32 }
+    }
+
+    fn co_occurrence_matrix(&self) -> Vec<Vec<f32>> {
+        let n = self.sdrs.len();
+        let mut p = vec![vec![0.0; n]; n];
+        for (i, a) in self.sdrs.iter().enumerate() {
+            for (j, b) in self.sdrs.iter().enumerate().skip(i + 1) {
+                let p_ij = Self::closeness(a, b);
+                p[i][j] = p_ij;
+                p[j][i] = p_ij;
+            }
+        }
+        p
+    }
+
+    /// One greedy SALSO pass: visit items in a random order, assigning each
+    /// to whichever existing cluster (or a brand-new singleton) minimizes
+    /// the incremental expected Binder loss. Returns the labels and their
+    /// total loss.
+    fn restart(p: &[Vec<f32>], n: usize, seed: u64, restart_index: u64) -> (Vec<usize>, f32) {
+        let mut rng_state = seed ^ restart_index.wrapping_mul(0x2545_f491_4f6c_dd1d) ^ 1;
+        let order = Self::shuffled_order(n, &mut rng_state);
+
+        let mut labels: Vec<Option<usize>> = vec![None; n];
+        let mut next_label = 0usize;
+
+        for &i in &order {
+            let existing_labels = Self::existing_labels(&labels);
+
+            let mut best_label = next_label;
+            let mut best_loss = Self::assignment_loss(p, &labels, i, next_label);
+            for &label in &existing_labels {
+                let loss = Self::assignment_loss(p, &labels, i, label);
+                if loss < best_loss {
+                    best_loss = loss;
+                    best_label = label;
+                }
+            }
+
+            if best_label == next_label { next_label += 1; }
+            labels[i] = Some(best_label);
+        }
+
+        let labels = labels.into_iter().map(|l| l.unwrap()).collect::<Vec<usize>>();
+        let loss = Self::total_loss(p, &labels);
+        (labels, loss)
+    }
+
+    /// Repeatedly reassigns each item to its best cluster given all the
+    /// others, until no reassignment lowers the total loss.
+    fn sweeten(p: &[Vec<f32>], mut labels: Vec<usize>) -> Vec<usize> {
+        let n = labels.len();
+
+        loop {
+            let mut improved = false;
+
+            for i in 0..n {
+                let without_i = labels.iter().enumerate()
+                    .map(|(j, &l)| if j == i { None } else { Some(l) })
+                    .collect::<Vec<Option<usize>>>();
+                let existing_labels = Self::existing_labels(&without_i);
+                let next_label = existing_labels.iter().max().map_or(0, |&m| m + 1);
+
+                let mut best_label = labels[i];
+                let mut best_loss = Self::assignment_loss(p, &without_i, i, labels[i]);
+                for &label in existing_labels.iter().chain(std::iter::once(&next_label)) {
+                    let loss = Self::assignment_loss(p, &without_i, i, label);
+                    if loss < best_loss {
+                        best_loss = loss;
+                        best_label = label;
+                    }
+                }
+
+                if best_label != labels[i] {
+                    labels[i] = best_label;
+                    improved = true;
+                }
+            }
+
+            if !improved { break; }
+        }
+
+        labels
+    }
+
+    fn existing_labels(labels: &[Option<usize>]) -> Vec<usize> {
+        let mut existing = labels.iter().filter_map(|&l| l).collect::<Vec<usize>>();
+        existing.sort_unstable();
+        existing.dedup();
+        existing
+    }
+
+    /// Incremental expected Binder loss of assigning item `i` to `label`,
+    /// given the items already labeled (`None` entries, including `i`
+    /// itself, are ignored).
+    fn assignment_loss(p: &[Vec<f32>], labels: &[Option<usize>], i: usize, label: usize) -> f32 {
+        labels.iter().enumerate()
+            .filter_map(|(j, &l)| l.map(|l| (j, l)))
+            .filter(|&(j, _)| j != i)
+            .map(|(j, l)| {
+                let p_ij = p[i][j];
+                if l == label { 1.0 - p_ij } else { p_ij }
+            })
+            .sum()
+    }
+
+    fn total_loss(p: &[Vec<f32>], labels: &[usize]) -> f32 {
+        let mut loss = 0.0;
+        for (i, &li) in labels.iter().enumerate() {
+            for (j, &lj) in labels.iter().enumerate().skip(i + 1) {
+                let p_ij = p[i][j];
+                loss += if li == lj { 1.0 - p_ij } else { p_ij };
+            }
+        }
+        loss
+    }
+
+    /// Fisher-Yates shuffle of `0..n` driven by a local xorshift64 state, so
+    /// restarts can run independently (and in parallel) without sharing
+    /// mutable RNG state.
+    fn shuffled_order(n: usize, rng_state: &mut u64) -> Vec<usize> {
+        let mut order = (0..n).collect::<Vec<usize>>();
+        for i in (1..n).rev() {
+            *rng_state ^= *rng_state << 13;
+            *rng_state ^= *rng_state >> 7;
+            *rng_state ^= *rng_state << 17;
+            let j = (*rng_state as usize) % (i + 1);
+            order.swap(i, j);
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sdr(active_bits: &[usize], len: usize) -> BitVec {
+        let mut bv = BitVec::from_elem(len, false);
+        for &b in active_bits { bv.set(b, true); }
+        bv
+    }
+
+    #[test]
+    fn separates_two_distinct_groups() {
+        let mut clusterer = SdrClusterer::default();
+
+        // Group A: mostly-overlapping SDRs active in the first half of the
+        // input space.
+        clusterer.add(sdr(&[0, 1, 2, 3, 4, 5], 20));
+        clusterer.add(sdr(&[0, 1, 2, 3, 4, 6], 20));
+        clusterer.add(sdr(&[1, 2, 3, 4, 5, 6], 20));
+
+        // Group B: mostly-overlapping SDRs active in the second half,
+        // disjoint from group A.
+        clusterer.add(sdr(&[14, 15, 16, 17, 18, 19], 20));
+        clusterer.add(sdr(&[13, 15, 16, 17, 18, 19], 20));
+        clusterer.add(sdr(&[14, 15, 16, 17, 18, 13], 20));
+
+        let labels = clusterer.cluster(8);
+
+        assert_eq!(labels[0], labels[1]);
+        assert_eq!(labels[1], labels[2]);
+        assert_eq!(labels[3], labels[4]);
+        assert_eq!(labels[4], labels[5]);
+        assert_ne!(labels[0], labels[3]);
+    }
+}
@@ -3,15 +3,41 @@ use std::cmp;
 use std::num::NonZeroU32;
 use std::convert::TryInto;
 
+/// Unsupervised clustering over accumulated spatial pooler outputs.
+pub mod cluster;
+
+/// Cells per column in the temporal memory.
+const CELLS_PER_COLUMN: usize = 32;
+/// Upper bound on how many synapses a freshly grown segment gets.
+const MAX_NEW_SYNAPSE_COUNT: usize = 20;
+
 /// Hierarchical temporal memory (HTM) layer.
 pub struct HTMLayer<const COLUMNS: usize> {
+    /// Only read by the `serde` round-trip (`HTMLayerData`); kept alongside
+    /// `new`'s other topology params so a saved layer can re-validate them.
+    #[cfg(feature = "serde")]
     input_length: usize,
+    /// Width of the input, in grid space (`input_width * input_height == input_length`).
+    #[cfg(feature = "serde")]
+    input_width: usize,
+    #[cfg(feature = "serde")]
+    input_height: usize,
+
+    /// Width of the column grid (`column_width * column_height == COLUMNS`).
+    column_width: usize,
+    column_height: usize,
+    /// Whether the column/input grids wrap around at the edges (toroidal topology).
+    wrap_around: bool,
+
     /// Global inhibition.
     num_active_columns_per_inhibition_area: usize,
     /// Local inhibition.
     inhibition_radius: usize,
 
     columns: [Column; COLUMNS],
+    /// Only read by the `serde` round-trip; `new` bakes this into each
+    /// column's `connected_synapses` instead of consulting it again.
+    #[cfg(feature = "serde")]
     potential_radius: usize,
 
     permanence_threshold: f32,
@@ -19,15 +45,26 @@ pub struct HTMLayer<const COLUMNS: usize> {
     permanence_decrement: f32,
 
     stimulus_threshold: f32,
+    /// Minimum number of connected synapses a distal segment needs onto
+    /// active cells to be considered active (temporal memory).
+    activation_threshold: usize,
 
     period: NonZeroU32,
-    min_overlap_duty_cycle: f32
+    min_overlap_duty_cycle: f32,
+
+    /// Cells active after the last `temporal_memory_step` call.
+    active_cells: BitVec,
+    /// Cells predicted to become active on the next `temporal_memory_step` call.
+    predictive_cells: BitVec,
+    /// Internal xorshift state, used to sample synapses when growing segments.
+    rng_state: u64
 
 }
 
 /// A cortical column.
 /// It connects to `HTMLayer`'s input with `potential_radius` synapses.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct Column {
     /// Each synapse has a permanence value.
     connected_synapses: Vec<(usize, f32)>,
@@ -35,11 +72,201 @@ struct Column {
     boost: f32,
 
     active_duty_cycle: f32,
-    overlap_duty_cycle: f32
+    overlap_duty_cycle: f32,
+
+    /// `CELLS_PER_COLUMN` temporal memory cells.
+    cells: Vec<Cell>
+}
+
+/// A temporal memory cell. A column bursts into all of its cells when none
+/// of them were predicted.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Cell {
+    /// Distal dendrite segments, each learning one transition.
+    segments: Vec<Segment>
+}
+
+/// A distal dendrite segment: a set of synapses onto other cells in the layer.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Segment {
+    /// `(target cell index, permanence)`. The target is a global cell index,
+    /// i.e. `column_index * CELLS_PER_COLUMN + cell_offset`.
+    synapses: Vec<(usize, f32)>
+}
+
+/// Plain-data mirror of `HTMLayer`, used to (de)serialize it.
+///
+/// `HTMLayer` itself can't derive `Serialize`/`Deserialize`: `columns` is a
+/// `[Column; COLUMNS]` (const-generic arrays only (de)serialize for a handful
+/// of fixed sizes), `period` is a `NonZeroU32`, and `active_cells` /
+/// `predictive_cells` are `BitVec`s. This mirror stores those as a `Vec`,
+/// a plain `u32` and `Vec<bool>`s instead, and `HTMLayer`'s manual
+/// `Deserialize` impl converts back while re-checking the invariants `new`
+/// would otherwise have enforced.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HTMLayerData {
+    input_length: usize,
+    input_width: usize,
+    input_height: usize,
+    column_width: usize,
+    column_height: usize,
+    wrap_around: bool,
+    num_active_columns_per_inhibition_area: usize,
+    inhibition_radius: usize,
+    columns: Vec<Column>,
+    potential_radius: usize,
+    permanence_threshold: f32,
+    permanence_increment: f32,
+    permanence_decrement: f32,
+    stimulus_threshold: f32,
+    activation_threshold: usize,
+    period: u32,
+    min_overlap_duty_cycle: f32,
+    active_cells: Vec<bool>,
+    predictive_cells: Vec<bool>,
+    rng_state: u64
+}
+
+#[cfg(feature = "serde")]
+impl<const COLUMNS: usize> serde::Serialize for HTMLayer<COLUMNS> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HTMLayerData {
+            input_length: self.input_length,
+            input_width: self.input_width,
+            input_height: self.input_height,
+            column_width: self.column_width,
+            column_height: self.column_height,
+            wrap_around: self.wrap_around,
+            num_active_columns_per_inhibition_area: self.num_active_columns_per_inhibition_area,
+            inhibition_radius: self.inhibition_radius,
+            columns: self.columns.to_vec(),
+            potential_radius: self.potential_radius,
+            permanence_threshold: self.permanence_threshold,
+            permanence_increment: self.permanence_increment,
+            permanence_decrement: self.permanence_decrement,
+            stimulus_threshold: self.stimulus_threshold,
+            activation_threshold: self.activation_threshold,
+            period: self.period.get(),
+            min_overlap_duty_cycle: self.min_overlap_duty_cycle,
+            active_cells: self.active_cells.iter().collect(),
+            predictive_cells: self.predictive_cells.iter().collect(),
+            rng_state: self.rng_state
+        }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const COLUMNS: usize> serde::Deserialize<'de> for HTMLayer<COLUMNS> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let data = HTMLayerData::deserialize(deserializer)?;
+
+        if data.columns.len() != COLUMNS {
+            return Err(D::Error::custom(format!("expected {} columns, found {}", COLUMNS, data.columns.len())));
+        }
+        if data.column_width * data.column_height != COLUMNS {
+            return Err(D::Error::custom("column_width * column_height must equal COLUMNS"));
+        }
+        if data.input_width * data.input_height != data.input_length {
+            return Err(D::Error::custom("input_width * input_height must equal input_length"));
+        }
+        if data.inhibition_radius <= data.num_active_columns_per_inhibition_area {
+            return Err(D::Error::custom("inhibition_radius must be greater than num_active_columns_per_inhibition_area"));
+        }
+        let total_cells = COLUMNS * CELLS_PER_COLUMN;
+        if data.active_cells.len() != total_cells || data.predictive_cells.len() != total_cells {
+            return Err(D::Error::custom("active_cells/predictive_cells must have COLUMNS * CELLS_PER_COLUMN bits"));
+        }
+        let period = NonZeroU32::new(data.period).ok_or_else(|| D::Error::custom("period must be non-zero"))?;
+        let columns: [Column; COLUMNS] = data.columns.try_into()
+            .unwrap_or_else(|_| unreachable!("column count was checked above"));
+
+        Ok(Self {
+            input_length: data.input_length,
+            input_width: data.input_width,
+            input_height: data.input_height,
+            column_width: data.column_width,
+            column_height: data.column_height,
+            wrap_around: data.wrap_around,
+            num_active_columns_per_inhibition_area: data.num_active_columns_per_inhibition_area,
+            inhibition_radius: data.inhibition_radius,
+            columns,
+            potential_radius: data.potential_radius,
+            permanence_threshold: data.permanence_threshold,
+            permanence_increment: data.permanence_increment,
+            permanence_decrement: data.permanence_decrement,
+            stimulus_threshold: data.stimulus_threshold,
+            activation_threshold: data.activation_threshold,
+            period,
+            min_overlap_duty_cycle: data.min_overlap_duty_cycle,
+            active_cells: data.active_cells.into_iter().collect(),
+            predictive_cells: data.predictive_cells.into_iter().collect(),
+            rng_state: data.rng_state
+        })
+    }
+}
+
+/// `(x, y)` grid position of linear index `i` on a `width`-wide grid.
+fn grid_pos(i: usize, width: usize) -> (usize, usize) {
+    (i % width, i / width)
+}
+
+/// Linear index of grid position `(x, y)` on a `width`-wide grid.
+fn grid_index(x: usize, y: usize, width: usize) -> usize {
+    y * width + x
+}
+
+/// Columns within `inhibition_radius` (Chebyshev distance) of `i` on a
+/// `grid_width x grid_height` grid, excluding `i` itself. With
+/// `wrap_around`, the grid is toroidal; otherwise it's clamped at the edges.
+/// Free function (rather than a method) so it can be called from inside a
+/// `&mut self.columns` borrow.
+fn neighbors_of(i: usize, inhibition_radius: usize, grid_width: usize, grid_height: usize, wrap_around: bool) -> Vec<usize> {
+    let (cx, cy) = grid_pos(i, grid_width);
+    let r = inhibition_radius as i32;
+
+    let mut neighbors_indices = Vec::new();
+    for dy in -r..=r {
+        for dx in -r..=r {
+            if dx == 0 && dy == 0 { continue; }
+
+            let (nx, ny) = if wrap_around {
+                (
+                    (cx as i32 + dx).rem_euclid(grid_width as i32),
+                    (cy as i32 + dy).rem_euclid(grid_height as i32)
+                )
+            } else {
+                let nx = cx as i32 + dx;
+                let ny = cy as i32 + dy;
+                if nx < 0 || nx >= grid_width as i32 || ny < 0 || ny >= grid_height as i32 {
+                    continue;
+                }
+                (nx, ny)
+            };
+
+            neighbors_indices.push(grid_index(nx as usize, ny as usize, grid_width));
+        }
+    }
+
+    // With `wrap_around` and a radius at least half a grid dimension, the
+    // same neighbor can be reached from more than one (dx, dy) offset.
+    // A radius of at least a full grid dimension can also wrap back onto `i`.
+    neighbors_indices.sort_unstable();
+    neighbors_indices.dedup();
+    neighbors_indices.retain(|&n| n != i);
+    neighbors_indices
 }
 
 impl<const COLUMNS: usize> HTMLayer<COLUMNS> {
-    pub fn new(input_length: usize,
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(column_width: usize, column_height: usize,
+               input_width: usize, input_height: usize,
+               wrap_around: bool,
+
                num_active_columns_per_inhibition_area: usize,
                inhibition_radius: usize,
 
@@ -49,41 +276,79 @@ impl<const COLUMNS: usize> HTMLayer<COLUMNS> {
                permanence_increment: f32, permanence_decrement: f32,
 
                stimulus_threshold: f32,
+               activation_threshold: usize,
 
                period: NonZeroU32,
                min_overlap_duty_cycle: f32) -> Self {
 
+        assert_eq!(column_width * column_height, COLUMNS);
         assert!(inhibition_radius > num_active_columns_per_inhibition_area);
 
-        // Attempt to scale `potential_radius` to cover all input.
-        let potential_radius = potential_radius * input_length / COLUMNS;
-        // Initialize columns with
-        // `potential_radius` random connections.
-        // 0.5 permanence and boost.
+        #[cfg(feature = "serde")]
+        let input_length = input_width * input_height;
 
+        // Initialize columns with one synapse onto every input bit within
+        // `potential_radius` (Chebyshev distance) of the column's grid
+        // position scaled into input space, 0.5 permanence and 10.0 boost.
         let columns = (0..COLUMNS).map(|i| {
-            let min = cmp::min(0, i as i32- potential_radius as i32) as usize;
-            let max = cmp::max(i + potential_radius, input_length);
+            let (cx, cy) = grid_pos(i, column_width);
+            let center_x = (cx * input_width / column_width) as i32;
+            let center_y = (cy * input_height / column_height) as i32;
+            let radius = potential_radius as i32;
+
+            let mut synapse_targets = Vec::new();
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let x = center_x + dx;
+                    let y = center_y + dy;
+
+                    let (x, y) = if wrap_around {
+                        (x.rem_euclid(input_width as i32), y.rem_euclid(input_height as i32))
+                    } else {
+                        if x < 0 || x >= input_width as i32 || y < 0 || y >= input_height as i32 {
+                            continue;
+                        }
+                        (x, y)
+                    };
 
-            let connected_synapses = (min..max).collect::<Vec<usize>>().iter()
-                .zip(vec![0.5; potential_radius])
-                .map(|(&synapse_i, p)| (synapse_i, p))
-                .collect::<Vec<(usize, f32)>>();
+                    synapse_targets.push(grid_index(x as usize, y as usize, input_width));
+                }
+            }
+
+            // As in `neighbors_of`, `wrap_around` can map more than one
+            // (dx, dy) offset onto the same input bit; don't grow duplicate
+            // synapses onto it.
+            synapse_targets.sort_unstable();
+            synapse_targets.dedup();
+            let connected_synapses = synapse_targets.into_iter().map(|target| (target, 0.5)).collect::<Vec<(usize, f32)>>();
+
+            let cells = (0..CELLS_PER_COLUMN).map(|_| Cell { segments: Vec::new() }).collect();
 
             Column {
                 connected_synapses,
                 boost: 10.0,
                 active_duty_cycle: 0.0,
-                overlap_duty_cycle: 0.0
+                overlap_duty_cycle: 0.0,
+                cells
             }
         }).collect::<Vec<Column>>().try_into().unwrap();
 
         Self {
+            #[cfg(feature = "serde")]
             input_length,
+            #[cfg(feature = "serde")]
+            input_width,
+            #[cfg(feature = "serde")]
+            input_height,
+            column_width,
+            column_height,
+            wrap_around,
+
             num_active_columns_per_inhibition_area,
             inhibition_radius,
 
             columns,
+            #[cfg(feature = "serde")]
             potential_radius,
 
             permanence_threshold,
@@ -91,129 +356,186 @@ impl<const COLUMNS: usize> HTMLayer<COLUMNS> {
             permanence_decrement,
 
             stimulus_threshold,
+            activation_threshold,
 
             period,
-            min_overlap_duty_cycle
+            min_overlap_duty_cycle,
+
+            active_cells: BitVec::from_elem(COLUMNS * CELLS_PER_COLUMN, false),
+            predictive_cells: BitVec::from_elem(COLUMNS * CELLS_PER_COLUMN, false),
+            rng_state: 0x2545_f491_4f6c_dd1d
         }
     }
 
     pub fn spatial_pooling_output(&mut self, input: &BitVec) -> BitVec {
-        // Overlap
+        let overlap = self.compute_overlap(input);
+        let active_columns = self.compute_active_columns(&overlap);
+
+        self.spatial_pooling_learning(&active_columns, &overlap);
+
+        active_columns
+    }
+
+    /// Overlap of every column's connected synapses with `input`, boosted.
+    /// Only reads `connected_synapses`/`boost`, so columns can be computed
+    /// independently of each other.
+    #[cfg(not(feature = "parallel"))]
+    fn compute_overlap(&self, input: &BitVec) -> Vec<f32> {
         let mut overlap = Vec::new();
         for i in 0..COLUMNS {
             overlap.push(0.);
             for (input_bit_index, _) in &self.columns[i].connected_synapses {
-                if input[*input_bit_index] == true { overlap[i] += 1.; }
+                if input[*input_bit_index] { overlap[i] += 1.; }
             }
             overlap[i] *= self.columns[i].boost;
         }
+        overlap
+    }
 
-        // Winning columns after inhibition
-        let mut active_columns = BitVec::new();
-        for i in 0..COLUMNS {
-            let min_local_activity = {
-                let neighbors = self.neighbors(i);
-
-                // kthScore
-                let mut local_overlap = Vec::new();
-                neighbors.iter().for_each(|&i| if overlap[i] > 0. { local_overlap.push(overlap[i]); });
-                local_overlap.sort_by(|a, b| a.partial_cmp(b).unwrap()); // Can't sort floats.
-
-                // I get 0 active columns, if I run twice.
-                if local_overlap.len() == 0 {
-                    0.0
-                } else if local_overlap.len() < self.num_active_columns_per_inhibition_area {
-                    local_overlap[0]
-                } else {
-                    let idx = cmp::max(0, local_overlap.len() as i32 - self.num_active_columns_per_inhibition_area as i32) as usize;
-                    local_overlap[idx]
-                }
-            };
+    #[cfg(feature = "parallel")]
+    fn compute_overlap(&self, input: &BitVec) -> Vec<f32> {
+        use rayon::prelude::*;
 
-            if overlap[i] > self.stimulus_threshold  && overlap[i] > min_local_activity {
-                active_columns.push(true);
-            } else {
-                active_columns.push(false);
+        (0..COLUMNS).into_par_iter().map(|i| {
+            let mut o = 0.;
+            for (input_bit_index, _) in &self.columns[i].connected_synapses {
+                if input[*input_bit_index] { o += 1.; }
             }
+            o * self.columns[i].boost
+        }).collect()
+    }
+
+    /// kthScore: the overlap a column needs to beat to win inhibition in its
+    /// neighborhood.
+    fn min_local_activity(&self, i: usize, overlap: &[f32]) -> f32 {
+        let neighbors = self.neighbors(i);
+
+        let mut local_overlap = Vec::new();
+        neighbors.iter().for_each(|&i| if overlap[i] > 0. { local_overlap.push(overlap[i]); });
+        local_overlap.sort_by(|a, b| a.partial_cmp(b).unwrap()); // Can't sort floats.
+
+        // I get 0 active columns, if I run twice.
+        if local_overlap.is_empty() {
+            0.0
+        } else if local_overlap.len() < self.num_active_columns_per_inhibition_area {
+            local_overlap[0]
+        } else {
+            let idx = cmp::max(0, local_overlap.len() as i32 - self.num_active_columns_per_inhibition_area as i32) as usize;
+            local_overlap[idx]
         }
+    }
 
-        self.spatial_pooling_learning(&active_columns, overlap.as_slice());
+    /// Winning columns after inhibition.
+    #[cfg(not(feature = "parallel"))]
+    fn compute_active_columns(&self, overlap: &[f32]) -> BitVec {
+        (0..COLUMNS)
+            .map(|i| overlap[i] > self.stimulus_threshold && overlap[i] > self.min_local_activity(i, overlap))
+            .collect()
+    }
 
-        active_columns
+    #[cfg(feature = "parallel")]
+    fn compute_active_columns(&self, overlap: &[f32]) -> BitVec {
+        use rayon::prelude::*;
+
+        (0..COLUMNS).into_par_iter()
+            .map(|i| overlap[i] > self.stimulus_threshold && overlap[i] > self.min_local_activity(i, overlap))
+            .collect::<Vec<bool>>()
+            .into_iter()
+            .collect()
     }
 
     fn spatial_pooling_learning(&mut self, sp_output: &BitVec, overlap: &[f32]) {
-        let columns_indices: Box<Vec<usize>> = Box::new(sp_output.iter()
-            .enumerate()
-            .map(|(i, _)| i)
-            .collect());
-        let active_columns_indices: Box<Vec<usize>> = Box::new(sp_output.iter()
-            .enumerate()
-            .filter(|(_, active)| *active) // Its value is either `true` (active) or `false` (inactive).
-            .map(|(i, _)| i)
-            .collect());
+        let permanence_threshold = self.permanence_threshold;
+        let permanence_increment = self.permanence_increment;
+        let permanence_decrement = self.permanence_decrement;
 
         // Learning
-        for i in active_columns_indices.into_iter() {
-            for (_, mut p) in &mut self.columns[i].connected_synapses {
-                if p > self.permanence_threshold {
-                    p += self.permanence_increment;
-                    if p < 1. {
-                        p = 1.0;
-                    };
-                } else {
-                    p -= self.permanence_decrement;
-                    if p > 1. {
-                        p = 1.0;
-                    }
-                }
+        #[cfg(not(feature = "parallel"))]
+        for i in 0..COLUMNS {
+            if sp_output[i] {
+                Self::reinforce_column(&mut self.columns[i], permanence_threshold, permanence_increment, permanence_decrement);
             }
+        }
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
 
+            self.columns.par_iter_mut().enumerate()
+                .filter(|(i, _)| sp_output[*i])
+                .for_each(|(_, column)| Self::reinforce_column(column, permanence_threshold, permanence_increment, permanence_decrement));
         }
 
         self.update_active_duty_cycle(sp_output);
         self.update_overlap_duty_cycle(overlap);
 
-        for i in columns_indices.into_iter() {
-            let neighbor_mean_active_duty_cycle = {
-                let i_neighbors_duty_cycles = self.neighbors(i).iter()
-                    .map(|&i_neighbor_index| self.columns[i_neighbor_index].active_duty_cycle)
-                    .collect::<Vec<f32>>();
+        let active_duty_cycles = self.columns.iter().map(|c| c.active_duty_cycle).collect::<Vec<f32>>();
+        let inhibition_radius = self.inhibition_radius;
+        let column_width = self.column_width;
+        let column_height = self.column_height;
+        let wrap_around = self.wrap_around;
+        let min_overlap_duty_cycle = self.min_overlap_duty_cycle;
 
-                i_neighbors_duty_cycles.iter().sum::<f32>() / i_neighbors_duty_cycles.len() as f32
-            };
+        #[cfg(not(feature = "parallel"))]
+        for i in 0..COLUMNS {
+            Self::update_boost_and_synapses(&mut self.columns[i], i, inhibition_radius, column_width, column_height, wrap_around,
+                                             &active_duty_cycles, min_overlap_duty_cycle, permanence_increment);
+        }
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+
+            self.columns.par_iter_mut().enumerate().for_each(|(i, column)| {
+                Self::update_boost_and_synapses(column, i, inhibition_radius, column_width, column_height, wrap_around,
+                                                 &active_duty_cycles, min_overlap_duty_cycle, permanence_increment);
+            });
+        }
+    }
 
-            // BoostFunction
-            self.columns[i].boost = if self.columns[i].active_duty_cycle >= neighbor_mean_active_duty_cycle {
-                self.columns[i].boost + 1.0
+    /// Increments synapses above `permanence_threshold`, decrements the rest.
+    fn reinforce_column(column: &mut Column, permanence_threshold: f32, permanence_increment: f32, permanence_decrement: f32) {
+        for (_, p) in &mut column.connected_synapses {
+            if *p > permanence_threshold {
+                *p += permanence_increment;
+                if *p > 1. {
+                    *p = 1.0;
+                };
             } else {
-                self.columns[i].boost - 1.0
-            };
-
-            // Increase permanence for all connected synapses
-            if self.columns[i].overlap_duty_cycle < self.min_overlap_duty_cycle {
-                for (_, mut p) in &mut self.columns[i].connected_synapses {
-                    p += self.permanence_increment;
+                *p -= permanence_decrement;
+                if *p > 1. {
+                    *p = 1.0;
                 }
             }
         }
     }
 
-    fn neighbors(&self, i: usize) -> Vec<usize> {
-        let mut neighbors_indices = Vec::new();
-        let rng_min = {
-            if (i as i32 - self.inhibition_radius as i32) < 0 {
-               0
-            } else { i - self.inhibition_radius }
+    /// BoostFunction, plus the min-overlap-duty-cycle permanence bump.
+    #[allow(clippy::too_many_arguments)]
+    fn update_boost_and_synapses(column: &mut Column, i: usize, inhibition_radius: usize, column_width: usize, column_height: usize,
+                                  wrap_around: bool, active_duty_cycles: &[f32], min_overlap_duty_cycle: f32, permanence_increment: f32) {
+        let neighbor_mean_active_duty_cycle = {
+            let i_neighbors_duty_cycles = neighbors_of(i, inhibition_radius, column_width, column_height, wrap_around).iter()
+                .map(|&i_neighbor_index| active_duty_cycles[i_neighbor_index])
+                .collect::<Vec<f32>>();
+
+            i_neighbors_duty_cycles.iter().sum::<f32>() / i_neighbors_duty_cycles.len() as f32
         };
-        let rng_max = {
-            if i + self.inhibition_radius >= COLUMNS {
-                COLUMNS - 1
-            } else { i + self.inhibition_radius }
+
+        column.boost = if column.active_duty_cycle >= neighbor_mean_active_duty_cycle {
+            column.boost + 1.0
+        } else {
+            column.boost - 1.0
         };
-        neighbors_indices.append(&mut (rng_min..i).collect::<Vec<usize>>());
-        neighbors_indices.append(&mut (i + 1..rng_max).collect::<Vec<usize>>());
-        neighbors_indices
+
+        // Increase permanence for all connected synapses
+        if column.overlap_duty_cycle < min_overlap_duty_cycle {
+            for (_, p) in &mut column.connected_synapses {
+                *p += permanence_increment;
+            }
+        }
+    }
+
+    fn neighbors(&self, i: usize) -> Vec<usize> {
+        neighbors_of(i, self.inhibition_radius, self.column_width, self.column_height, self.wrap_around)
     }
 
     fn update_active_duty_cycle(&mut self, active_columns: &BitVec) {
@@ -223,8 +545,234 @@ impl<const COLUMNS: usize> HTMLayer<COLUMNS> {
     }
 
     fn update_overlap_duty_cycle(&mut self, overlap: &[f32]) {
-        for i in 0..COLUMNS {
-            self.columns[i].overlap_duty_cycle = (self.columns[i].overlap_duty_cycle * (self.period.get() - 1) as f32 + overlap[i]) / self.period.get() as f32;
+        let period = self.period.get() as f32;
+        for (column, &o) in self.columns.iter_mut().zip(overlap) {
+            column.overlap_duty_cycle = (column.overlap_duty_cycle * (period - 1.) + o) / period;
+        }
+    }
+
+    /// Run one step of temporal memory on top of `spatial_pooling_output`'s
+    /// `active_columns`. Returns the cells that are now active, and the
+    /// cells that had been predicted to become active (i.e. the ones that
+    /// `active_columns` was checked against).
+    pub fn temporal_memory_step(&mut self, active_columns: &BitVec) -> (BitVec, BitVec) {
+        let prior_active_cells = self.active_cells.clone();
+        let prior_predictive_cells = self.predictive_cells.clone();
+
+        let mut active_cells = BitVec::from_elem(COLUMNS * CELLS_PER_COLUMN, false);
+        for column_index in 0..COLUMNS {
+            if !active_columns[column_index] { continue; }
+
+            let cell_base = column_index * CELLS_PER_COLUMN;
+            let predicted_cells = (0..CELLS_PER_COLUMN)
+                .filter(|&c| prior_predictive_cells[cell_base + c])
+                .collect::<Vec<usize>>();
+
+            if predicted_cells.is_empty() {
+                // No cell in this column was predicted: burst.
+                for c in 0..CELLS_PER_COLUMN {
+                    active_cells.set(cell_base + c, true);
+                }
+            } else {
+                for c in predicted_cells {
+                    active_cells.set(cell_base + c, true);
+                }
+            }
         }
+
+        self.temporal_memory_learning(active_columns, &prior_active_cells, &prior_predictive_cells);
+
+        self.active_cells = active_cells.clone();
+        self.predictive_cells = self.compute_predictive_cells(&self.active_cells.clone());
+
+        (active_cells, prior_predictive_cells)
+    }
+
+    /// Reinforces segments that correctly predicted an active column, and
+    /// teaches bursting columns the transition from `prior_active_cells`.
+    fn temporal_memory_learning(&mut self, active_columns: &BitVec, prior_active_cells: &BitVec, prior_predictive_cells: &BitVec) {
+        let prior_active_indices = prior_active_cells.iter().enumerate()
+            .filter(|(_, active)| *active)
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+
+        for column_index in 0..COLUMNS {
+            if !active_columns[column_index] { continue; }
+
+            let cell_base = column_index * CELLS_PER_COLUMN;
+            let predicted_cells = (0..CELLS_PER_COLUMN)
+                .filter(|&c| prior_predictive_cells[cell_base + c])
+                .collect::<Vec<usize>>();
+
+            if !predicted_cells.is_empty() {
+                for c in predicted_cells {
+                    for segment in &mut self.columns[column_index].cells[c].segments {
+                        Self::reinforce_segment(segment, prior_active_cells, self.permanence_threshold,
+                                                 self.permanence_increment, self.permanence_decrement, self.activation_threshold);
+                    }
+                }
+                continue;
+            }
+
+            // Bursting column: reinforce the best-matching segment, or grow
+            // a new one onto the least-used cell.
+            let best_match = self.columns[column_index].cells.iter().enumerate()
+                .flat_map(|(c, cell)| cell.segments.iter().enumerate().map(move |(s, segment)| (c, s, segment)))
+                .map(|(c, s, segment)| (c, s, segment.synapses.iter().filter(|&&(target, _)| prior_active_cells[target]).count()))
+                .filter(|&(_, _, matching)| matching > 0)
+                .max_by_key(|&(_, _, matching)| matching);
+
+            match best_match {
+                Some((c, s, _)) => {
+                    // This segment fell short of `activation_threshold` (or its
+                    // cell would have been predicted), so reinforce it ungated.
+                    let segment = &mut self.columns[column_index].cells[c].segments[s];
+                    Self::reinforce_segment(segment, prior_active_cells, self.permanence_threshold,
+                                             self.permanence_increment, self.permanence_decrement, 0);
+                },
+                None => {
+                    if prior_active_indices.is_empty() { continue; }
+
+                    let growth_cell = self.columns[column_index].cells.iter()
+                        .enumerate()
+                        .min_by_key(|(_, cell)| cell.segments.len())
+                        .map(|(c, _)| c)
+                        .unwrap();
+
+                    let synapses = self.sample_new_synapses(&prior_active_indices);
+                    self.columns[column_index].cells[growth_cell].segments.push(Segment { synapses });
+                }
+            }
+        }
+    }
+
+    /// Increments synapses onto active cells, decrements the rest of a
+    /// segment that was (or would have been) active.
+    fn reinforce_segment(segment: &mut Segment, prior_active_cells: &BitVec, permanence_threshold: f32,
+                          permanence_increment: f32, permanence_decrement: f32, activation_threshold: usize) {
+        let was_active = segment.synapses.iter()
+            .filter(|&&(target, permanence)| permanence > permanence_threshold && prior_active_cells[target])
+            .count() >= activation_threshold;
+        if !was_active { return; }
+
+        for (target, permanence) in &mut segment.synapses {
+            if prior_active_cells[*target] {
+                *permanence = (*permanence + permanence_increment).min(1.0);
+            } else {
+                *permanence = (*permanence - permanence_decrement).max(0.0);
+            }
+        }
+    }
+
+    /// Computes which cells have a segment with at least `activation_threshold`
+    /// connected synapses onto `active_cells`.
+    fn compute_predictive_cells(&self, active_cells: &BitVec) -> BitVec {
+        let mut predictive = BitVec::from_elem(COLUMNS * CELLS_PER_COLUMN, false);
+        for column_index in 0..COLUMNS {
+            for (cell_offset, cell) in self.columns[column_index].cells.iter().enumerate() {
+                let is_predictive = cell.segments.iter().any(|segment| {
+                    segment.synapses.iter()
+                        .filter(|&&(target, permanence)| permanence > self.permanence_threshold && active_cells[target])
+                        .count() >= self.activation_threshold
+                });
+                predictive.set(column_index * CELLS_PER_COLUMN + cell_offset, is_predictive);
+            }
+        }
+        predictive
+    }
+
+    /// Picks up to `MAX_NEW_SYNAPSE_COUNT` distinct targets out of
+    /// `prior_active_indices` for a newly grown segment.
+    fn sample_new_synapses(&mut self, prior_active_indices: &[usize]) -> Vec<(usize, f32)> {
+        let mut candidates = prior_active_indices.to_vec();
+        let sample_size = cmp::min(MAX_NEW_SYNAPSE_COUNT, candidates.len());
+
+        // Partial Fisher-Yates shuffle.
+        for i in 0..sample_size {
+            let j = i + (self.next_random() as usize) % (candidates.len() - i);
+            candidates.swap(i, j);
+        }
+
+        candidates[..sample_size].iter().map(|&target| (target, 0.5)).collect()
+    }
+
+    /// xorshift64, enough randomness to sample synapses without pulling in a
+    /// `rand` dependency.
+    fn next_random(&mut self) -> u64 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        self.rng_state
+    }
+
+    /// Writes this layer (permanences, boosts, duty cycles and temporal
+    /// memory segments) to `w` so it can be restored later with `load`.
+    #[cfg(feature = "serde")]
+    pub fn save<W: std::io::Write>(&self, w: W) -> bincode::Result<()> {
+        bincode::serialize_into(w, self)
+    }
+
+    /// Restores a layer previously written by `save`. The file is not
+    /// trusted: column count and the `inhibition_radius` /
+    /// `num_active_columns_per_inhibition_area` invariant are re-checked,
+    /// the same way `new` would enforce them.
+    #[cfg(feature = "serde")]
+    pub fn load<R: std::io::Read>(r: R) -> bincode::Result<Self> {
+        bincode::deserialize_from(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_layer() -> HTMLayer<4> {
+        HTMLayer::<4>::new(2, 2,
+                            2, 2,
+                            false,
+                            1, 2,
+                            1,
+                            0.2, 0.1, 0.1,
+                            0.2, 1,
+                            NonZeroU32::new(4).unwrap(), 0.0)
+    }
+
+    fn active_columns(columns: &[usize]) -> BitVec {
+        let mut bv = BitVec::from_elem(4, false);
+        for &c in columns { bv.set(c, true); }
+        bv
+    }
+
+    /// On first exposure to a column a cell hasn't been predicted in, the
+    /// whole column bursts; once a transition has been seen once, the
+    /// learned segment should predict (and thus narrow) the next burst.
+    #[test]
+    fn bursts_then_predicts_on_repeated_transition() {
+        let mut layer = small_layer();
+
+        // Step 1: columns {0, 1} active with nothing learned yet -> burst.
+        let (active1, _) = layer.temporal_memory_step(&active_columns(&[0, 1]));
+        let column0_active = (0..CELLS_PER_COLUMN).filter(|&c| active1[c]).count();
+        assert_eq!(column0_active, CELLS_PER_COLUMN, "first exposure should burst the whole column");
+
+        // Step 2: columns {2, 3} active. Nothing predicted them either, so
+        // they burst too, and in doing so grow segments onto step 1's cells.
+        layer.temporal_memory_step(&active_columns(&[2, 3]));
+
+        // Step 3: columns {0, 1} active again, reactivating the cells the
+        // step 2 segments were grown onto -- this should make columns 2/3
+        // predictive for the next step.
+        layer.temporal_memory_step(&active_columns(&[0, 1]));
+        let column2_predicted = (0..CELLS_PER_COLUMN).any(|c| layer.predictive_cells[2 * CELLS_PER_COLUMN + c]);
+        let column3_predicted = (0..CELLS_PER_COLUMN).any(|c| layer.predictive_cells[3 * CELLS_PER_COLUMN + c]);
+        assert!(column2_predicted && column3_predicted, "repeated transition should be predicted");
+
+        // Step 4: columns {2, 3} active again -- since they were predicted,
+        // only the predicted cell should activate, not the whole column.
+        let (active4, _) = layer.temporal_memory_step(&active_columns(&[2, 3]));
+        let column2_active = (0..CELLS_PER_COLUMN).filter(|&c| active4[2 * CELLS_PER_COLUMN + c]).count();
+        let column3_active = (0..CELLS_PER_COLUMN).filter(|&c| active4[3 * CELLS_PER_COLUMN + c]).count();
+        assert_eq!(column2_active, 1, "predicted column should activate only its predicted cell");
+        assert_eq!(column3_active, 1, "predicted column should activate only its predicted cell");
     }
 }
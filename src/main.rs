@@ -13,14 +13,24 @@ fn main() {
         ip.push(true);
     }
 
-    let mut my_layer = HTMLayer::<2048>::new(ip.len(),
-                                 8, 10, 10,
+    let mut my_layer = HTMLayer::<2048>::new(64, 32,
+                                 50, 40,
+                                 false,
+                                 8, 10,
+                                 10,
                                  2.0, 8.0, 2.0,
-                                 1.0,
+                                 1.0, 10,
                                  NonZeroU32::new(4).unwrap(),2.0);
 
     let active_columns = my_layer.spatial_pooling_output(&ip);
     println!("Active columns = {:?}.", active_columns);
+    let (active_cells, predicted_cells) = my_layer.temporal_memory_step(&active_columns);
+    println!("Active cells = {:?}.", active_cells);
+    println!("Predicted cells = {:?}.", predicted_cells);
+
     let active_columns = my_layer.spatial_pooling_output(&ip);
     println!("Active columns = {:?}.", active_columns);
+    let (active_cells, predicted_cells) = my_layer.temporal_memory_step(&active_columns);
+    println!("Active cells = {:?}.", active_cells);
+    println!("Predicted cells = {:?}.", predicted_cells);
 }